@@ -1,3 +1,5 @@
+use gcplog_rs::HttpRequest;
+use std::time::Instant;
 use tiny_http::{Response, Server};
 use tracing::{info, info_span};
 
@@ -24,13 +26,26 @@ fn main() {
         };
         let _guard = span.enter();
 
-        info!("received request: {} {}", request.method(), request.url());
+        let method = request.method().to_string();
+        let url = request.url().to_string();
+        let started_at = Instant::now();
+
+        let body = "Hello, World!";
+        let response = Response::from_string(body);
+        let http_request = HttpRequest {
+            request_method: Some(method),
+            request_url: Some(url),
+            status: Some(200),
+            response_size: Some(body.len() as u64),
+            latency: Some(format!("{:.3}s", started_at.elapsed().as_secs_f64())),
+            protocol: Some("HTTP/1.1".to_string()),
+            ..Default::default()
+        };
 
-        let response = Response::from_string("Hello, World!");
         if let Err(e) = request.respond(response) {
-            tracing::error!(error = ?e, "failed to send response");
+            tracing::error!(error = ?e, http_request = ?http_request, "failed to send response");
         } else {
-            info!("response sent");
+            info!(http_request = ?http_request, "served");
         }
     }
 }
@@ -0,0 +1,61 @@
+//! A typed `HttpRequest` field, so request metadata can be recorded structurally instead
+//! of as free text and rendered in Cloud Logging's request-aware UI.
+
+use serde::Serialize;
+
+/// Mirrors Cloud Logging's `HttpRequest` type. Record one as an event field named
+/// `http_request` (e.g. `info!(http_request = ?req, "served")`) to have it serialized
+/// under `logging.googleapis.com/httpRequest` instead of the generic `jsonPayload`.
+///
+/// See <https://cloud.google.com/logging/docs/reference/v2/rest/v2/LogEntry#HttpRequest>.
+#[derive(Clone, Default, Serialize)]
+pub struct HttpRequest {
+    #[serde(rename = "requestMethod")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_method: Option<String>,
+    #[serde(rename = "requestUrl")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u32>,
+    #[serde(rename = "responseSize")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_size: Option<u64>,
+    #[serde(rename = "userAgent")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+    #[serde(rename = "remoteIp")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote_ip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<String>,
+}
+
+/// `tracing`'s `Visit` trait only exposes recorded struct fields as `&dyn Debug`, so this
+/// renders as the struct's own JSON serialization. [`FieldVisitor`](crate) recovers the
+/// structured value by parsing it back out of the Debug output, the same trick `message`
+/// relies on to round-trip through `record_debug` untouched.
+impl std::fmt::Debug for HttpRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&serde_json::to_string(self).map_err(|_| std::fmt::Error)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debug_output_is_json() {
+        let req = HttpRequest {
+            request_method: Some("GET".to_string()),
+            status: Some(200),
+            ..Default::default()
+        };
+        let parsed: serde_json::Value = serde_json::from_str(&format!("{req:?}")).unwrap();
+        assert_eq!(parsed["requestMethod"], "GET");
+        assert_eq!(parsed["status"], 200);
+    }
+}
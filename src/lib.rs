@@ -1,46 +1,168 @@
+mod http_request;
+mod resource;
+mod sink;
+
 use chrono::{SecondsFormat, Utc};
 use serde::Serialize;
-use serde_json::to_string;
+use serde_json::{from_str, to_string, to_value, Map, Value};
+use std::collections::BTreeMap;
 use std::env;
 use tracing::field::{Field, Visit};
 use tracing::span::{Attributes, Id};
-use tracing::{Event, Subscriber};
+use tracing::{Event, Level, Subscriber};
 use tracing_subscriber::filter::LevelFilter;
 use tracing_subscriber::layer::Context;
 use tracing_subscriber::prelude::*;
 use tracing_subscriber::registry::LookupSpan;
 use tracing_subscriber::{registry, Layer};
 
-struct TraceId(String);
+pub use http_request::HttpRequest;
+pub use resource::MonitoredResource;
+pub use sink::ApiSinkGuard;
 
-#[derive(Default)]
-struct TraceIdVisitor {
-    trace_id: Option<String>,
+/// The parsed components of a trace context, whether from a bare trace ID or a full
+/// W3C `traceparent` header.
+struct TraceId {
+    trace_id: String,
+    span_id: Option<String>,
+    sampled: bool,
 }
 
-impl Visit for TraceIdVisitor {
-    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
-        if field.name() == "trace_id" {
-            self.trace_id = Some(format!("{value:?}"))
+/// Parse a `trace_id` span field into its trace/span/sampled components.
+///
+/// If `value` matches the W3C Trace Context `traceparent` format
+/// (`version-traceid-spanid-flags`, e.g.
+/// `00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`), the trace ID, span ID, and
+/// sampled flag are extracted from it. Otherwise `value` is treated as a bare trace ID.
+fn parse_trace_id(value: &str) -> TraceId {
+    let parts: Vec<&str> = value.split('-').collect();
+    let is_traceparent = parts.len() == 4
+        && parts[0].len() == 2
+        && parts[1].len() == 32
+        && parts[2].len() == 16
+        && parts[3].len() == 2
+        && parts
+            .iter()
+            .all(|p| p.chars().all(|c| c.is_ascii_hexdigit()));
+
+    if is_traceparent {
+        let flags = u8::from_str_radix(parts[3], 16).unwrap_or(0);
+        TraceId {
+            trace_id: parts[1].to_string(),
+            span_id: Some(parts[2].to_string()),
+            sampled: flags & 0x01 != 0,
+        }
+    } else {
+        TraceId {
+            trace_id: value.to_string(),
+            span_id: None,
+            sampled: false,
         }
     }
 }
 
+/// Fields recorded on a span, to be merged into every event emitted within its scope.
+#[derive(Default, Clone)]
+struct SpanFields {
+    fields: Map<String, Value>,
+    labels: BTreeMap<String, String>,
+}
+
+/// Collects every field recorded on a span or event into a JSON object, routing fields
+/// named like `label.region` into a separate `labels` map per Cloud Logging's indexed-label
+/// convention. `trace_id` is tracked separately since it drives `logging.googleapis.com/trace`,
+/// and `http_request` since it drives `logging.googleapis.com/httpRequest`, rather than either
+/// ending up in the `jsonPayload`.
 #[derive(Default)]
-struct EventVisitor {
+struct FieldVisitor {
+    trace_id: Option<String>,
     message: Option<String>,
+    severity: Option<String>,
+    http_request: Option<Value>,
+    fields: Map<String, Value>,
+    labels: BTreeMap<String, String>,
 }
 
-impl Visit for EventVisitor {
-    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
-        if field.name() == "message" {
-            self.message = Some(format!("{value:?}"))
+impl FieldVisitor {
+    fn record(&mut self, field: &Field, value: Value) {
+        match field.name() {
+            "trace_id" => self.trace_id = value.as_str().map(str::to_string),
+            "message" => self.message = value.as_str().map(str::to_string),
+            "severity" => self.severity = value.as_str().map(str::to_string),
+            "http_request" => {
+                // Recorded via `?req`, so `value` is `HttpRequest`'s Debug output, which it
+                // renders as its own JSON serialization (see `http_request::HttpRequest`).
+                if let Some(json) = value.as_str().and_then(|s| from_str(s).ok()) {
+                    self.http_request = Some(json);
+                }
+            }
+            name => {
+                if let Some(label) = name.strip_prefix("label.") {
+                    let value = match value {
+                        Value::String(s) => s,
+                        other => other.to_string(),
+                    };
+                    self.labels.insert(label.to_string(), value);
+                } else {
+                    self.fields.insert(name.to_string(), value);
+                }
+            }
         }
     }
 }
 
+impl Visit for FieldVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.record(field, Value::String(format!("{value:?}")));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record(field, Value::String(value.to_string()));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.record(field, Value::Bool(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.record(field, Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.record(field, Value::from(value));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.record(field, Value::from(value));
+    }
+}
+
+/// Map a tracing [`Level`] to a Google Cloud `LogSeverity` value.
+///
+/// See https://cloud.google.com/logging/docs/reference/v2/rest/v2/LogEntry#LogSeverity.
+/// `NOTICE`, `CRITICAL`, `ALERT`, and `EMERGENCY` have no tracing equivalent and can only
+/// be reached by recording an explicit `severity` field on the event.
+fn level_to_severity(level: &Level) -> &'static str {
+    match *level {
+        Level::TRACE | Level::DEBUG => "DEBUG",
+        Level::INFO => "INFO",
+        Level::WARN => "WARNING",
+        Level::ERROR => "ERROR",
+    }
+}
+
 struct GcpLayer {
     gcp_project_id: String,
+    sink: Sink,
+    resource: MonitoredResource,
+}
+
+/// Where emitted [`LogEntry`] values end up.
+enum Sink {
+    /// One JSON line per event on stderr, for a collector agent (e.g. Cloud Run) to ingest.
+    Stderr,
+    /// Batched directly to the Cloud Logging API by a background thread.
+    Api(sink::ApiSink),
 }
 
 #[derive(Serialize)]
@@ -51,16 +173,30 @@ struct SourceLocation {
 }
 
 #[derive(Serialize)]
-struct LogEntry<'a> {
-    severity: &'a str,
+struct LogEntry {
+    severity: String,
     message: String,
     time: String,
     #[serde(rename = "logging.googleapis.com/trace")]
     #[serde(skip_serializing_if = "Option::is_none")]
     trace: Option<String>,
+    #[serde(rename = "logging.googleapis.com/spanId")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    span_id: Option<String>,
+    #[serde(rename = "logging.googleapis.com/trace_sampled")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trace_sampled: Option<bool>,
     #[serde(rename = "logging.googleapis.com/sourceLocation")]
     #[serde(skip_serializing_if = "Option::is_none")]
     source_location: Option<SourceLocation>,
+    #[serde(rename = "logging.googleapis.com/labels")]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    labels: BTreeMap<String, String>,
+    #[serde(rename = "logging.googleapis.com/httpRequest")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    http_request: Option<Value>,
+    #[serde(flatten)]
+    fields: Map<String, Value>,
 }
 
 impl<S> Layer<S> for GcpLayer
@@ -69,28 +205,46 @@ where
 {
     fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
         if let Some(span) = ctx.span(id) {
-            let mut visitor = TraceIdVisitor::default();
+            let mut visitor = FieldVisitor::default();
             attrs.record(&mut visitor);
+            let mut extensions = span.extensions_mut();
             if let Some(trace_id) = visitor.trace_id {
-                span.extensions_mut().insert(TraceId(trace_id));
+                extensions.insert(parse_trace_id(&trace_id));
             }
+            extensions.insert(SpanFields {
+                fields: visitor.fields,
+                labels: visitor.labels,
+            });
         };
     }
 
     fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
         let mut trace = None;
+        let mut span_id = None;
+        let mut trace_sampled = None;
+        let mut fields = Map::new();
+        let mut labels = BTreeMap::new();
         if let Some(scope) = ctx.event_scope(event) {
             for span in scope.from_root() {
                 let extensions = span.extensions();
-                let Some(trace_id) = extensions.get::<TraceId>() else {
-                    continue;
-                };
-                let t = &trace_id.0;
-                trace = Some(format!("projects/{}/traces/{t}", self.gcp_project_id));
+                if let Some(trace_id) = extensions.get::<TraceId>() {
+                    trace = Some(format!(
+                        "projects/{}/traces/{}",
+                        self.gcp_project_id, trace_id.trace_id
+                    ));
+                    span_id = trace_id.span_id.clone();
+                    trace_sampled = trace_id.sampled.then_some(true);
+                }
+                if let Some(span_fields) = extensions.get::<SpanFields>() {
+                    fields.extend(span_fields.fields.clone());
+                    labels.extend(span_fields.labels.clone());
+                }
             }
         }
-        let mut visitor = EventVisitor::default();
+        let mut visitor = FieldVisitor::default();
         event.record(&mut visitor);
+        fields.extend(visitor.fields);
+        labels.extend(visitor.labels);
 
         let metadata = event.metadata();
         let source_location = metadata.file().map(|file| SourceLocation {
@@ -99,32 +253,68 @@ where
             function: metadata.target().to_string(),
         });
 
+        let severity = visitor
+            .severity
+            .unwrap_or_else(|| level_to_severity(metadata.level()).to_string());
+
         let entry = LogEntry {
-            severity: event.metadata().level().as_str(),
+            severity,
             message: visitor.message.unwrap_or_default(),
             time: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
             trace,
+            span_id,
+            trace_sampled,
             source_location,
+            labels,
+            http_request: visitor.http_request,
+            fields,
+        };
+        let Value::Object(mut entry) = to_value(&entry).unwrap() else {
+            unreachable!("LogEntry always serializes to a JSON object");
         };
-        eprintln!("{}", to_string(&entry).unwrap());
+        match &self.sink {
+            Sink::Stderr => {
+                entry.insert(
+                    "logging.googleapis.com/resource".to_string(),
+                    to_value(&self.resource).unwrap(),
+                );
+                eprintln!("{}", to_string(&entry).unwrap());
+            }
+            // The API-ingestion request carries the resource at the batch level instead
+            // (see `sink::flush`), so it's left off the individual entry here.
+            Sink::Api(api) => api.send(Value::Object(entry)),
+        }
     }
 }
 
-/// Fetch the GCP project ID from the metadata service.
-///
-/// This queries the GCP metadata service at http://169.254.169.254/computeMetadata/v1/project/project-id
-/// The metadata host can be overridden via the GCE_METADATA_HOST environment variable.
-fn fetch_project_id() -> Result<String, Box<dyn std::error::Error>> {
-    let host = env::var("GCE_METADATA_HOST").unwrap_or_else(|_| "169.254.169.254".to_string());
-    let url = format!("http://{}/computeMetadata/v1/project/project-id", host);
+/// The metadata service host, overridable via the `GCE_METADATA_HOST` environment variable.
+pub(crate) fn metadata_host() -> String {
+    env::var("GCE_METADATA_HOST").unwrap_or_else(|_| "169.254.169.254".to_string())
+}
+
+/// Fetch a `computeMetadata/v1/<path>` value from the GCP metadata service.
+pub(crate) fn fetch_metadata(path: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let url = format!("http://{}/computeMetadata/v1/{path}", metadata_host());
 
     let response = ureq::get(&url)
         .set("Metadata-Flavor", "Google")
         .timeout(std::time::Duration::from_secs(2))
         .call()?;
 
-    let project_id = response.into_string()?.trim().to_string();
-    Ok(project_id)
+    Ok(response.into_string()?.trim().to_string())
+}
+
+/// Fetch the GCP project ID from the metadata service.
+fn fetch_project_id() -> Result<String, Box<dyn std::error::Error>> {
+    fetch_metadata("project/project-id")
+}
+
+/// Where [`init`] should send emitted log entries.
+enum Output {
+    /// One JSON line per event on stderr (the default).
+    Stderr,
+    /// Batched directly to the `entries:write` Cloud Logging API, under this log name.
+    Api { log_name: String },
 }
 
 /// Configuration for the GCP structured logging subscriber.
@@ -134,6 +324,8 @@ pub struct Config {
     pub gcp_project_id: Option<String>,
     /// The minimum log level to emit (defaults to INFO if not specified)
     pub level_filter: Option<LevelFilter>,
+    output: Output,
+    resource: Option<MonitoredResource>,
 }
 
 impl Config {
@@ -143,6 +335,8 @@ impl Config {
         Self {
             gcp_project_id: None,
             level_filter: None,
+            output: Output::Stderr,
+            resource: None,
         }
     }
 
@@ -152,6 +346,8 @@ impl Config {
         Self {
             gcp_project_id: Some(gcp_project_id.into()),
             level_filter: None,
+            output: Output::Stderr,
+            resource: None,
         }
     }
 
@@ -160,6 +356,26 @@ impl Config {
         self.level_filter = Some(level);
         self
     }
+
+    /// Override the monitored resource that log entries are associated with, instead of
+    /// auto-detecting it from the GCP metadata service and environment.
+    pub fn with_resource(mut self, resource: MonitoredResource) -> Self {
+        self.resource = Some(resource);
+        self
+    }
+
+    /// Write log entries directly to the Cloud Logging API under `log_name`, instead of
+    /// to stderr. Use this on plain GCE/self-managed hosts that have no collector agent
+    /// (e.g. Cloud Run) ingesting stderr output.
+    ///
+    /// [`init`] returns an [`ApiSinkGuard`] when this is set; keep it alive for the
+    /// lifetime of the program so buffered entries are flushed on exit.
+    pub fn with_api_ingestion(mut self, log_name: impl Into<String>) -> Self {
+        self.output = Output::Api {
+            log_name: log_name.into(),
+        };
+        self
+    }
 }
 
 impl Default for Config {
@@ -209,15 +425,49 @@ impl Default for Config {
 /// gcplog_rs::init(config);
 /// info!("Application started");
 /// ```
-pub fn init(config: Config) {
+///
+/// ```no_run
+/// use tracing::info;
+///
+/// // Write directly to the Cloud Logging API instead of stderr. Keep the returned
+/// // guard alive so buffered entries are flushed when the program exits.
+/// let config = gcplog_rs::Config::with_project_id("my-project-123")
+///     .with_api_ingestion("my-app");
+/// let _guard = gcplog_rs::init(config);
+/// info!("Application started");
+/// ```
+///
+/// # Returns
+///
+/// An [`ApiSinkGuard`] when [`Config::with_api_ingestion`] was used, `None` otherwise. The
+/// guard must be kept alive for the lifetime of the program; dropping it flushes any
+/// entries still buffered for the Cloud Logging API.
+pub fn init(config: Config) -> Option<ApiSinkGuard> {
     let gcp_project_id = config
         .gcp_project_id
         .or_else(|| fetch_project_id().ok())
         .unwrap_or_else(|| "unknown".to_string());
 
-    let layer = GcpLayer { gcp_project_id };
+    let resource = config
+        .resource
+        .unwrap_or_else(|| resource::detect(&gcp_project_id));
+
+    let (sink, guard) = match config.output {
+        Output::Stderr => (Sink::Stderr, None),
+        Output::Api { log_name } => {
+            let (api_sink, guard) = sink::spawn(gcp_project_id.clone(), log_name, resource.clone());
+            (Sink::Api(api_sink), Some(guard))
+        }
+    };
+
+    let layer = GcpLayer {
+        gcp_project_id,
+        sink,
+        resource,
+    };
     let level_filter = config.level_filter.unwrap_or(LevelFilter::INFO);
     registry().with(layer.with_filter(level_filter)).init();
+    guard
 }
 
 #[cfg(test)]
@@ -237,5 +487,73 @@ mod tests {
         let _guard = span.enter();
         info!("Processing request");
         warn!("Potential issue detected");
+
+        // Test explicit severity override
+        info!(severity = "NOTICE", "Unusual but expected event");
+
+        // Test log with a full W3C traceparent header
+        let span = info_span!(
+            "trace_id",
+            trace_id = %"00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+        );
+        let _guard = span.enter();
+        info!("Handling traced request");
+
+        // Test structured fields and labels, including fields inherited from an
+        // enclosing instrumented span
+        let span = info_span!("request", user_id = 42, "label.region" = "us-central1");
+        let _guard = span.enter();
+        info!(path = "/x", "label.env" = "prod", "handled");
+
+        // Test the dedicated httpRequest field
+        let http_request = HttpRequest {
+            request_method: Some("GET".to_string()),
+            status: Some(200),
+            ..Default::default()
+        };
+        info!(http_request = ?http_request, "served");
+    }
+
+    #[test]
+    fn test_parse_trace_id_traceparent() {
+        let parsed = parse_trace_id("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01");
+        assert_eq!(parsed.trace_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(parsed.span_id.as_deref(), Some("00f067aa0ba902b7"));
+        assert!(parsed.sampled);
+    }
+
+    #[test]
+    fn test_parse_trace_id_not_sampled() {
+        let parsed = parse_trace_id("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00");
+        assert!(!parsed.sampled);
+    }
+
+    #[test]
+    fn test_parse_trace_id_bare() {
+        let parsed = parse_trace_id("abc123");
+        assert_eq!(parsed.trace_id, "abc123");
+        assert_eq!(parsed.span_id, None);
+        assert!(!parsed.sampled);
+    }
+
+    #[test]
+    fn test_with_api_ingestion_sets_output() {
+        let config = Config::with_project_id("test-project-123").with_api_ingestion("test-log");
+        match config.output {
+            Output::Api { log_name } => assert_eq!(log_name, "test-log"),
+            Output::Stderr => panic!("expected Api output"),
+        }
+    }
+
+    #[test]
+    fn test_with_resource_overrides_auto_detection() {
+        let mut labels = BTreeMap::new();
+        labels.insert("project_id".to_string(), "test-project-123".to_string());
+        let resource = MonitoredResource {
+            r#type: "gce_instance".to_string(),
+            labels,
+        };
+        let config = Config::with_project_id("test-project-123").with_resource(resource);
+        assert!(matches!(config.resource, Some(r) if r.r#type == "gce_instance"));
     }
 }
@@ -0,0 +1,120 @@
+//! Detects the GCP monitored resource (GCE instance, Cloud Run revision, ...) that log
+//! entries should be associated with, the same way journaldriver derives it from the
+//! metadata service and well-known Cloud Run environment variables.
+
+use crate::fetch_metadata;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::env;
+
+/// A GCP monitored resource descriptor, as used by both the stderr and API-ingestion
+/// output modes. See <https://cloud.google.com/monitoring/api/resources>.
+#[derive(Clone, Serialize)]
+pub struct MonitoredResource {
+    #[serde(rename = "type")]
+    pub r#type: String,
+    pub labels: BTreeMap<String, String>,
+}
+
+/// Detect the monitored resource this process is running on.
+///
+/// Checks for the Cloud Run environment variables (`K_SERVICE`, `K_REVISION`,
+/// `K_CONFIGURATION`) first, then falls back to GCE instance metadata, and finally to the
+/// generic `global` resource if neither is available (e.g. running locally).
+pub(crate) fn detect(gcp_project_id: &str) -> MonitoredResource {
+    if let Ok(service_name) = env::var("K_SERVICE") {
+        return cloud_run_revision(gcp_project_id, service_name);
+    }
+    if let Ok(zone) = fetch_metadata("instance/zone") {
+        return gce_instance(gcp_project_id, &zone);
+    }
+    global(gcp_project_id)
+}
+
+fn cloud_run_revision(gcp_project_id: &str, service_name: String) -> MonitoredResource {
+    let location = fetch_metadata("instance/zone")
+        .ok()
+        .and_then(|zone| region_from_zone(&zone))
+        .unwrap_or_else(|| "unknown".to_string());
+
+    let mut labels = BTreeMap::new();
+    labels.insert("project_id".to_string(), gcp_project_id.to_string());
+    labels.insert("service_name".to_string(), service_name);
+    labels.insert(
+        "revision_name".to_string(),
+        env::var("K_REVISION").unwrap_or_default(),
+    );
+    labels.insert(
+        "configuration_name".to_string(),
+        env::var("K_CONFIGURATION").unwrap_or_default(),
+    );
+    labels.insert("location".to_string(), location);
+
+    MonitoredResource {
+        r#type: "cloud_run_revision".to_string(),
+        labels,
+    }
+}
+
+fn gce_instance(gcp_project_id: &str, zone: &str) -> MonitoredResource {
+    let mut labels = BTreeMap::new();
+    labels.insert("project_id".to_string(), gcp_project_id.to_string());
+    labels.insert(
+        "instance_id".to_string(),
+        fetch_metadata("instance/id").unwrap_or_default(),
+    );
+    labels.insert("zone".to_string(), last_path_segment(zone).to_string());
+
+    MonitoredResource {
+        r#type: "gce_instance".to_string(),
+        labels,
+    }
+}
+
+fn global(gcp_project_id: &str) -> MonitoredResource {
+    let mut labels = BTreeMap::new();
+    labels.insert("project_id".to_string(), gcp_project_id.to_string());
+    MonitoredResource {
+        r#type: "global".to_string(),
+        labels,
+    }
+}
+
+/// The metadata service returns zone/region paths like
+/// `projects/123456789/zones/us-central1-a`; this returns the final segment.
+fn last_path_segment(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+/// Derive the region (e.g. `us-central1`) from a zone name (e.g. `us-central1-a`).
+fn region_from_zone(zone: &str) -> Option<String> {
+    let zone = last_path_segment(zone);
+    let (region, _suffix) = zone.rsplit_once('-')?;
+    Some(region.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_region_from_zone() {
+        assert_eq!(
+            region_from_zone("projects/123456789/zones/us-central1-a"),
+            Some("us-central1".to_string())
+        );
+        assert_eq!(
+            region_from_zone("not-a-zone-path"),
+            Some("not-a-zone".to_string())
+        );
+    }
+
+    #[test]
+    fn test_last_path_segment() {
+        assert_eq!(
+            last_path_segment("projects/123456789/zones/us-central1-a"),
+            "us-central1-a"
+        );
+        assert_eq!(last_path_segment("us-central1-a"), "us-central1-a");
+    }
+}
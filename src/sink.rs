@@ -0,0 +1,217 @@
+//! A background sink that batches log entries and writes them directly to the Cloud
+//! Logging API, as an alternative to the stderr/collector-agent path in `lib.rs`.
+
+use crate::MonitoredResource;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const BATCH_SIZE: usize = 1000;
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+const MAX_RETRIES: u32 = 5;
+
+enum SinkMessage {
+    Entry(Value),
+    Shutdown,
+}
+
+/// Handle used by [`crate::GcpLayer`] to hand entries off to the background writer thread.
+#[derive(Clone)]
+pub(crate) struct ApiSink {
+    sender: mpsc::Sender<SinkMessage>,
+}
+
+impl ApiSink {
+    pub(crate) fn send(&self, entry: Value) {
+        // If the background thread has already shut down there's nowhere left to send
+        // this entry; drop it rather than panicking the logging layer.
+        let _ = self.sender.send(SinkMessage::Entry(entry));
+    }
+}
+
+/// Returned by [`crate::init`] when [`crate::Config::with_api_ingestion`] is used. Keep
+/// this alive for the lifetime of the program (e.g. bind it in `main`); dropping it blocks
+/// until any buffered log entries have been flushed to Cloud Logging.
+#[must_use = "dropping the guard flushes and stops the API ingestion thread"]
+pub struct ApiSinkGuard {
+    sender: mpsc::Sender<SinkMessage>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl Drop for ApiSinkGuard {
+    fn drop(&mut self) {
+        let _ = self.sender.send(SinkMessage::Shutdown);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Spawn the background thread that batches and flushes entries to the Cloud Logging API.
+pub(crate) fn spawn(
+    gcp_project_id: String,
+    log_name: String,
+    resource: MonitoredResource,
+) -> (ApiSink, ApiSinkGuard) {
+    let (sender, receiver) = mpsc::channel();
+    let handle = thread::spawn(move || run(receiver, gcp_project_id, log_name, resource));
+    (
+        ApiSink {
+            sender: sender.clone(),
+        },
+        ApiSinkGuard {
+            sender,
+            handle: Some(handle),
+        },
+    )
+}
+
+fn run(
+    receiver: mpsc::Receiver<SinkMessage>,
+    gcp_project_id: String,
+    log_name: String,
+    resource: MonitoredResource,
+) {
+    let log_name = format!("projects/{gcp_project_id}/logs/{log_name}");
+    let mut token = OAuthToken::default();
+    let mut batch = Vec::new();
+    let mut deadline = Instant::now() + FLUSH_INTERVAL;
+
+    loop {
+        match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(SinkMessage::Entry(entry)) => {
+                batch.push(entry);
+                if batch.len() >= BATCH_SIZE {
+                    flush(&mut batch, &log_name, &resource, &mut token);
+                    deadline = Instant::now() + FLUSH_INTERVAL;
+                }
+            }
+            Ok(SinkMessage::Shutdown) => {
+                while let Ok(SinkMessage::Entry(entry)) = receiver.try_recv() {
+                    batch.push(entry);
+                    if batch.len() >= BATCH_SIZE {
+                        flush(&mut batch, &log_name, &resource, &mut token);
+                    }
+                }
+                flush(&mut batch, &log_name, &resource, &mut token);
+                return;
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                flush(&mut batch, &log_name, &resource, &mut token);
+                deadline = Instant::now() + FLUSH_INTERVAL;
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                flush(&mut batch, &log_name, &resource, &mut token);
+                return;
+            }
+        }
+    }
+}
+
+/// POST the buffered entries to `entries:write`, retrying transient failures with backoff.
+fn flush(
+    batch: &mut Vec<Value>,
+    log_name: &str,
+    resource: &MonitoredResource,
+    token: &mut OAuthToken,
+) {
+    if batch.is_empty() {
+        return;
+    }
+
+    let body = json!({
+        "logName": log_name,
+        "resource": resource,
+        "entries": batch,
+    });
+
+    for attempt in 0..=MAX_RETRIES {
+        let Some(access_token) = token.get() else {
+            eprintln!(
+                "gcplog_rs: failed to acquire an OAuth token, dropping {} log entries",
+                batch.len()
+            );
+            break;
+        };
+
+        match ureq::post("https://logging.googleapis.com/v2/entries:write")
+            .set("Authorization", &format!("Bearer {access_token}"))
+            .send_json(body.clone())
+        {
+            Ok(_) => break,
+            Err(err) if attempt < MAX_RETRIES && is_retryable(&err) => {
+                // The cached token may be the thing that's actually failing (e.g. revoked
+                // early, clock skew); drop it so the next attempt fetches a fresh one
+                // instead of retrying with the same bad token until retries run out.
+                token.token = None;
+                thread::sleep(Duration::from_millis(200 * 2u64.pow(attempt)));
+            }
+            Err(err) => {
+                eprintln!(
+                    "gcplog_rs: failed to write {} log entries: {err}",
+                    batch.len()
+                );
+                break;
+            }
+        }
+    }
+
+    batch.clear();
+}
+
+fn is_retryable(err: &ureq::Error) -> bool {
+    match err {
+        ureq::Error::Status(status, _) => *status >= 500,
+        ureq::Error::Transport(_) => true,
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+/// Caches the metadata service's OAuth access token until it's close to expiry.
+#[derive(Default)]
+struct OAuthToken {
+    token: Option<String>,
+    expires_at: Option<Instant>,
+}
+
+impl OAuthToken {
+    fn get(&mut self) -> Option<String> {
+        if let (Some(token), Some(expires_at)) = (&self.token, self.expires_at) {
+            if Instant::now() < expires_at {
+                return Some(token.clone());
+            }
+        }
+        let (token, ttl) = fetch_access_token().ok()?;
+        self.token = Some(token.clone());
+        // Refresh a little early so a batch never starts with an about-to-expire token.
+        self.expires_at = Some(Instant::now() + ttl.saturating_sub(Duration::from_secs(30)));
+        Some(token)
+    }
+}
+
+/// Fetch an OAuth access token for the instance's default service account from the GCP
+/// metadata service.
+fn fetch_access_token() -> Result<(String, Duration), Box<dyn std::error::Error>> {
+    let url = format!(
+        "http://{}/computeMetadata/v1/instance/service-accounts/default/token",
+        crate::metadata_host()
+    );
+
+    let response: TokenResponse = ureq::get(&url)
+        .set("Metadata-Flavor", "Google")
+        .timeout(Duration::from_secs(2))
+        .call()?
+        .into_json()?;
+
+    Ok((
+        response.access_token,
+        Duration::from_secs(response.expires_in),
+    ))
+}